@@ -9,10 +9,13 @@
 //! let slot_count = 100;
 //! let slot_for_key = jh.slot(&"key", slot_count);
 //! ```
+//!
+//! Enable the `ahash` feature to additionally get `AHashJumpHasher`, a faster,
+//! AES-accelerated alternative to the default SipHash-based hasher.
 
 use rand::RngCore;
 use siphasher::sip::SipHasher13;
-use std::hash::{Hash, Hasher};
+use std::hash::{BuildHasher, Hash, Hasher};
 
 /// A default jump hash instance with the default, recommended hash function.
 #[derive(Clone, Copy, Debug)]
@@ -59,6 +62,58 @@ impl JumpHasher {
     }
 }
 
+/// A jump hash instance using the AES-accelerated `ahash` function instead of SipHash.
+///
+/// It is still a keyed hash seeded from two random 64-bit values, so it keeps the
+/// same DOS-resistance against adversarial keys as the SipHash-based `JumpHasher`:
+/// an attacker who doesn't know the keys can't force collisions. It is, however,
+/// not a cryptographically secure hash function and shouldn't be used where that
+/// property is required -- only pick it for the throughput it buys over SipHash.
+#[cfg(feature = "ahash")]
+#[derive(Clone, Debug)]
+pub struct AHashJumpHasher {
+    bh: ahash::RandomState,
+}
+
+#[cfg(feature = "ahash")]
+impl Default for AHashJumpHasher {
+    /// Returns a non-deterministic `AHashJumpHasher` structure.
+    fn default() -> AHashJumpHasher {
+        let mut rng = rand::thread_rng();
+        Self::new_with_keys(rng.next_u64(), rng.next_u64())
+    }
+}
+
+#[cfg(feature = "ahash")]
+impl AHashJumpHasher {
+    /// Returns a non-deterministic `AHashJumpHasher` structure.
+    pub fn new() -> AHashJumpHasher {
+        AHashJumpHasher::default()
+    }
+
+    /// Returns a deterministic `AHashJumpHasher` structure, seeded with two 64-bit keys.
+    #[inline]
+    pub fn new_with_keys(k1: u64, k2: u64) -> AHashJumpHasher {
+        AHashJumpHasher {
+            bh: ahash::RandomState::with_seeds(k1, k2, k1, k2),
+        }
+    }
+
+    /// Returns a slot for the key `key`, out of `slot_count` available slots.
+    pub fn slot<T: Hash>(&self, key: &T, slot_count: u32) -> u32 {
+        debug_assert!(slot_count > 0);
+        let mut h = self.bh.hash_one(key);
+        let (mut b, mut j) = (-1i64, 0i64);
+        while j < slot_count as i64 {
+            b = j;
+            h = h.wrapping_mul(2862933555777941757).wrapping_add(1);
+            j = ((b.wrapping_add(1) as f64) * (((1u64 << 31) as f64) / (((h >> 33) + 1) as f64)))
+                as i64;
+        }
+        b as u32
+    }
+}
+
 /// A jump hash instance with a custom hash function.
 #[derive(Clone, Copy, Debug)]
 pub struct CustomJumpHasher<H: Hasher + Clone> {
@@ -90,6 +145,182 @@ impl<H: Hasher + Clone> CustomJumpHasher<H> {
     }
 }
 
+/// A jump hash instance backed by a `BuildHasher`, such as `std::collections::hash_map::RandomState`.
+///
+/// Unlike [`CustomJumpHasher`], which requires a `Clone`-able `Hasher` and clones it on
+/// every `slot()` call, this builds a fresh `Hasher` from the `BuildHasher` each time, so
+/// it works with `RandomState` and other `BuildHasher`s that don't implement `Clone`. This
+/// is the easiest way to reuse whatever hashing strategy a `HashMap` in the same
+/// application already uses.
+#[derive(Clone, Copy, Debug)]
+pub struct BuildHasherJumpHasher<B: BuildHasher> {
+    bh: B,
+}
+
+impl<B: BuildHasher> BuildHasherJumpHasher<B> {
+    /// Initializes a jump hash instance with a custom `BuildHasher`.
+    pub fn new(build_hasher: B) -> BuildHasherJumpHasher<B> {
+        BuildHasherJumpHasher { bh: build_hasher }
+    }
+
+    /// Returns a slot for the key `key`, out of `slot_count` available slots.
+    pub fn slot<T: Hash>(&self, key: &T, slot_count: u32) -> u32 {
+        debug_assert!(slot_count > 0);
+        let mut h = self.bh.hash_one(key);
+        let (mut b, mut j) = (-1i64, 0i64);
+        while j < slot_count as i64 {
+            b = j;
+            h = h.wrapping_mul(2862933555777941757).wrapping_add(1);
+            j = ((b.wrapping_add(1) as f64) * (((1u64 << 31) as f64) / (((h >> 33) + 1) as f64)))
+                as i64;
+        }
+        b as u32
+    }
+}
+
+/// A jump hash instance for slots with different weights, e.g. backends with different capacities.
+///
+/// Each key is first mapped onto one of `sum(weights)` equally-sized virtual slots using
+/// the usual jump hash loop, then the virtual slot is resolved to the physical slot whose
+/// cumulative weight range contains it. A physical slot therefore receives a share of the
+/// keyspace proportional to its weight, and a zero-weight slot never receives any key.
+#[derive(Clone, Debug)]
+pub struct WeightedJumpHasher {
+    jh: JumpHasher,
+    cumulative_weights: Vec<u64>,
+}
+
+impl WeightedJumpHasher {
+    /// Initializes a weighted jump hash instance for slots with the given `weights`.
+    pub fn new(jh: JumpHasher, weights: &[u32]) -> WeightedJumpHasher {
+        debug_assert!(!weights.is_empty(), "weights must not be empty");
+        let mut total_weight = 0u64;
+        let cumulative_weights = weights
+            .iter()
+            .map(|&w| {
+                total_weight = total_weight
+                    .checked_add(w as u64)
+                    .expect("total weight overflows u64");
+                total_weight
+            })
+            .collect();
+        debug_assert!(total_weight > 0, "all slots have zero weight");
+        debug_assert!(
+            total_weight <= i64::MAX as u64,
+            "total weight overflows i64"
+        );
+        WeightedJumpHasher {
+            jh,
+            cumulative_weights,
+        }
+    }
+
+    /// Returns a slot for the key `key`, out of the weighted slots this instance was created with.
+    pub fn slot<T: Hash>(&self, key: &T) -> u32 {
+        let total_weight = *self
+            .cumulative_weights
+            .last()
+            .expect("weights must not be empty");
+        let mut hs = self.jh.hs;
+        key.hash(&mut hs);
+        let mut h = hs.finish();
+        let (mut b, mut j) = (-1i64, 0i64);
+        let slot_count = total_weight as i64;
+        while j < slot_count {
+            b = j;
+            h = h.wrapping_mul(2862933555777941757).wrapping_add(1);
+            j = ((b.wrapping_add(1) as f64) * (((1u64 << 31) as f64) / (((h >> 33) + 1) as f64)))
+                as i64;
+        }
+        let v = b as u64;
+        self.cumulative_weights.partition_point(|&c| c <= v) as u32
+    }
+}
+
+/// A fixed-size jump hash ring that supports removing arbitrary slots, not just the
+/// highest-indexed one.
+///
+/// Plain jump hashing can only shrink from the top: dropping slot `slot_count - 1`
+/// remaps just the keys that land there, but taking an arbitrary slot out of the
+/// middle -- as happens when a specific node in a cluster fails -- isn't supported.
+/// `JumpRing` tracks a set of removed slots over a fixed `slot_count`; a key that
+/// lands on a surviving slot stays there, and a key that lands on a removed slot is
+/// deterministically rehashed onto a surviving slot, independent of removal order.
+#[derive(Clone, Debug)]
+pub struct JumpRing {
+    jh: JumpHasher,
+    slot_count: u32,
+    removed: Vec<u32>,
+}
+
+impl JumpRing {
+    /// Creates a new ring of `slot_count` slots, none of which are removed.
+    pub fn new(jh: JumpHasher, slot_count: u32) -> JumpRing {
+        debug_assert!(slot_count > 0);
+        JumpRing {
+            jh,
+            slot_count,
+            removed: Vec::new(),
+        }
+    }
+
+    /// Marks `slot` as removed, so `slot()` never returns it again.
+    ///
+    /// Panics if `slot` is out of range, or if it is the last surviving slot.
+    pub fn remove(&mut self, slot: u32) {
+        assert!(slot < self.slot_count, "slot out of range");
+        if let Err(i) = self.removed.binary_search(&slot) {
+            assert!(
+                self.removed.len() + 1 < self.slot_count as usize,
+                "cannot remove the last surviving slot"
+            );
+            self.removed.insert(i, slot);
+        }
+    }
+
+    /// Restores a previously removed `slot`.
+    pub fn restore(&mut self, slot: u32) {
+        if let Ok(i) = self.removed.binary_search(&slot) {
+            self.removed.remove(i);
+        }
+    }
+
+    /// Returns `true` if `slot` has been removed.
+    pub fn is_removed(&self, slot: u32) -> bool {
+        self.removed.binary_search(&slot).is_ok()
+    }
+
+    /// Returns a slot for the key `key`, never returning a removed slot.
+    pub fn slot<T: Hash>(&self, key: &T) -> u32 {
+        let (mut seed, mut round) = (0u64, 0u64);
+        loop {
+            let b = self.jump_slot(key, seed);
+            if !self.is_removed(b) {
+                return b;
+            }
+            round += 1;
+            seed = (b as u64).wrapping_add(round.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+        }
+    }
+
+    /// Runs the jump hash loop for `key` over `self.slot_count` slots, with `seed` mixed
+    /// into the key digest so that reseeding on a removed slot is deterministic.
+    fn jump_slot<T: Hash>(&self, key: &T, seed: u64) -> u32 {
+        let mut hs = self.jh.hs;
+        key.hash(&mut hs);
+        seed.hash(&mut hs);
+        let mut h = hs.finish();
+        let (mut b, mut j) = (-1i64, 0i64);
+        while j < self.slot_count as i64 {
+            b = j;
+            h = h.wrapping_mul(2862933555777941757).wrapping_add(1);
+            j = ((b.wrapping_add(1) as f64) * (((1u64 << 31) as f64) / (((h >> 33) + 1) as f64)))
+                as i64;
+        }
+        b as u32
+    }
+}
+
 #[test]
 fn test_basic() {
     let j = JumpHasher::new_with_keys(0, 0);
@@ -106,6 +337,18 @@ fn test_basic() {
     assert_ne!(JumpHasher::new().slot(&"test2", 1000), h0);
 }
 
+#[cfg(feature = "ahash")]
+#[test]
+fn test_ahash() {
+    let j = AHashJumpHasher::new_with_keys(0, 0);
+    let j2 = AHashJumpHasher::new_with_keys(0, 0);
+    assert_eq!(j.slot(&"test1", 1000), j2.slot(&"test1", 1000));
+    assert_eq!(j.slot(&"testz", 1), 0);
+    let j = AHashJumpHasher::new();
+    let h0 = j.slot(&"test2", 1000);
+    assert_ne!(AHashJumpHasher::new().slot(&"test2", 1000), h0);
+}
+
 #[test]
 fn test_custom_hash() {
     let j = CustomJumpHasher::new(SipHasher13::new_with_keys(0, 0));
@@ -125,3 +368,82 @@ fn test_custom_hash() {
         h0
     );
 }
+
+#[test]
+fn test_build_hasher() {
+    use std::collections::hash_map::RandomState;
+
+    let j = BuildHasherJumpHasher::new(RandomState::new());
+    assert_eq!(j.slot(&"test2", 1000), j.slot(&"test2", 1000));
+    let h0 = j.slot(&"test2", 1000);
+    assert_ne!(
+        BuildHasherJumpHasher::new(RandomState::new()).slot(&"test2", 1000),
+        h0
+    );
+}
+
+#[test]
+fn test_weighted() {
+    let j = WeightedJumpHasher::new(JumpHasher::new_with_keys(0, 0), &[0, 1, 3]);
+    // Slot 0 has no weight, so it must never be picked.
+    for i in 0..10000 {
+        assert_ne!(j.slot(&i), 0);
+    }
+    // Roughly 3x more keys should land on slot 2 than on slot 1.
+    let (mut count1, mut count2) = (0u32, 0u32);
+    for i in 0..10000 {
+        match j.slot(&i) {
+            1 => count1 += 1,
+            2 => count2 += 1,
+            _ => unreachable!(),
+        }
+    }
+    let ratio = f64::from(count2) / f64::from(count1);
+    assert!((2.5..3.5).contains(&ratio), "ratio was {}", ratio);
+}
+
+#[test]
+fn test_ring() {
+    let jh = JumpHasher::new_with_keys(0, 0);
+    let mut ring = JumpRing::new(jh, 1000);
+
+    let before: Vec<u32> = (0..1000u32).map(|i| ring.slot(&i)).collect();
+
+    // Removing a slot in the middle must only move keys that were on it.
+    ring.remove(500);
+    assert!(ring.is_removed(500));
+    for (i, &b) in before.iter().enumerate() {
+        let i = i as u32;
+        let after = ring.slot(&i);
+        assert_ne!(after, 500);
+        if b != 500 {
+            assert_eq!(after, b);
+        }
+    }
+
+    // Restoring the slot must bring those keys back to where they started.
+    ring.restore(500);
+    assert!(!ring.is_removed(500));
+    for (i, &b) in before.iter().enumerate() {
+        let i = i as u32;
+        assert_eq!(ring.slot(&i), b);
+    }
+}
+
+#[test]
+fn test_ring_all_but_one_removed() {
+    let mut ring = JumpRing::new(JumpHasher::new_with_keys(0, 0), 3);
+    ring.remove(0);
+    ring.remove(1);
+    for i in 0..100 {
+        assert_eq!(ring.slot(&i), 2);
+    }
+}
+
+#[test]
+#[should_panic(expected = "cannot remove the last surviving slot")]
+fn test_ring_cannot_remove_last_slot() {
+    let mut ring = JumpRing::new(JumpHasher::new_with_keys(0, 0), 2);
+    ring.remove(0);
+    ring.remove(1);
+}